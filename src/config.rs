@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// TOML-driven customization of the underlying `prost_build`/`tonic_build` configuration,
+/// applied on top of the generator's defaults.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// `(path, attribute)` pairs passed to `prost_build::Config::type_attribute`.
+    pub type_attribute: Vec<(String, String)>,
+    /// `(path, attribute)` pairs passed to `prost_build::Config::field_attribute`.
+    pub field_attribute: Vec<(String, String)>,
+    /// Paths passed to `prost_build::Config::bytes`, generating `Bytes` instead of `Vec<u8>`.
+    pub bytes: Vec<String>,
+    /// Paths passed to `prost_build::Config::btree_map`.
+    pub btree_map: Vec<String>,
+    /// `(proto_path, rust_path)` pairs passed to `prost_build::Config::extern_path`.
+    pub extern_path: Vec<(String, String)>,
+    /// Whether to generate a tonic server implementation. Defaults to `true`.
+    pub build_server: bool,
+    /// Whether to generate a tonic client implementation. Defaults to `true`.
+    pub build_client: bool,
+    /// Whether tonic should compile well-known types instead of using `prost-types`.
+    pub compile_well_known_types: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            type_attribute: Vec::new(),
+            field_attribute: Vec::new(),
+            bytes: Vec::new(),
+            btree_map: Vec::new(),
+            extern_path: Vec::new(),
+            build_server: true,
+            build_client: true,
+            compile_well_known_types: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("read config file ({})", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("parse config file ({})", path.display()))
+    }
+
+    pub fn apply_to_prost(&self, config: &mut prost_build::Config) {
+        for (path, attribute) in &self.type_attribute {
+            config.type_attribute(path, attribute);
+        }
+        for (path, attribute) in &self.field_attribute {
+            config.field_attribute(path, attribute);
+        }
+        if !self.bytes.is_empty() {
+            config.bytes(&self.bytes);
+        }
+        if !self.btree_map.is_empty() {
+            config.btree_map(&self.btree_map);
+        }
+        for (proto_path, rust_path) in &self.extern_path {
+            config.extern_path(proto_path, rust_path);
+        }
+    }
+
+    pub fn apply_to_tonic(&self, builder: tonic_build::Builder) -> tonic_build::Builder {
+        builder
+            .build_server(self.build_server)
+            .build_client(self.build_client)
+            .compile_well_known_types(self.compile_well_known_types)
+    }
+}
@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Errors produced while resolving the transitive set of `.proto` files reachable
+/// from a set of entry points.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("circular import: `{import}` is imported from `{current}`, which is still being resolved")]
+    CircularImport { current: String, import: String },
+    #[error(
+        "import `{import}` required by `{importer}` could not be found; searched: {}",
+        searched.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    MissingImportFile {
+        importer: String,
+        import: String,
+        searched: Vec<PathBuf>,
+    },
+}
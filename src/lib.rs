@@ -0,0 +1,415 @@
+//! Generates a Rust crate from a tree of protobuf definitions.
+//!
+//! The [`Generator`] is the reusable core behind the `protocrate` CLI. It can also be driven
+//! straight from a `build.rs`, writing the generated module tree into `OUT_DIR` and `include!`d
+//! from `lib.rs`, rather than committing a generated crate to the repository. The
+//! [`compile_protos!`] macro wraps the common case of that call.
+
+mod config;
+mod error;
+mod module;
+mod resolver;
+
+pub use config::Config;
+pub use error::Error;
+pub use resolver::EntryPoint;
+
+use anyhow::{Context, Result};
+use codegen::Scope;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+
+use module::Module;
+
+/// Generates a Rust crate from a tree of protobuf definitions.
+///
+/// Construct one with [`Generator::builder`] and run it with [`Generator::generate`].
+#[derive(Clone, Debug)]
+pub struct Generator {
+    output_dir: PathBuf,
+    roots: Vec<String>,
+    pkg_name: String,
+    pkg_version: String,
+    pkg_author: Vec<String>,
+    cargo_toml_template: Option<PathBuf>,
+    rustfmt: bool,
+    descriptor_set_path: Option<PathBuf>,
+    entry: Vec<String>,
+    entry_file: Vec<PathBuf>,
+    external_module: HashMap<String, String>,
+    reflection: bool,
+    config_path: Option<PathBuf>,
+    json: bool,
+}
+
+impl Generator {
+    /// Starts building a [`Generator`] that writes to `output_dir` and names the generated
+    /// crate `pkg_name`.
+    pub fn builder(output_dir: impl Into<PathBuf>, pkg_name: impl Into<String>) -> GeneratorBuilder {
+        GeneratorBuilder::new(output_dir.into(), pkg_name.into())
+    }
+
+    /// Runs the generator, writing the generated crate to `output_dir`.
+    pub fn generate(&self) -> Result<()> {
+        let src_dir = self.output_dir.join("src");
+        let resources_dir = self.output_dir.join("resources");
+        let _ignore_err = std::fs::remove_dir_all(&src_dir);
+        fs::create_dir_all(&resources_dir)
+            .context(format!("create dir ({})", resources_dir.display()))?;
+        fs::create_dir_all(&src_dir).context(format!("create dir ({})", src_dir.display()))?;
+        let descriptor_path = self
+            .descriptor_set_path
+            .clone()
+            .unwrap_or_else(|| resources_dir.join("file_descriptor_set.bin"));
+
+        {
+            // Find all .proto files in any of the root paths, unless entry points were given, in
+            // which case only protos transitively reachable from them are compiled.
+            let proto_paths: Vec<String> = if self.entry.is_empty() && self.entry_file.is_empty() {
+                let mut proto_paths: Vec<String> = self
+                    .roots
+                    .iter()
+                    .flat_map(|path| {
+                        WalkDir::new(path)
+                            .into_iter()
+                            .filter_map(|e| e.ok())
+                            .filter(|e| e.path().extension() == Some(OsStr::new("proto")))
+                            .map(|e| e.path().to_str().unwrap().to_owned())
+                    })
+                    .collect();
+                proto_paths.sort();
+                proto_paths
+            } else {
+                // Entry-point resolution already returns a valid compilation order (imports
+                // before importers); re-sorting alphabetically would discard that.
+                let entries: Vec<EntryPoint> = self
+                    .entry
+                    .iter()
+                    .cloned()
+                    .map(EntryPoint::Package)
+                    .chain(self.entry_file.iter().cloned().map(EntryPoint::File))
+                    .collect();
+                resolver::resolve(&self.roots, &entries)?
+            };
+
+            let toml_config = match &self.config_path {
+                Some(config_path) => Config::from_file(config_path)?,
+                None => Config::default(),
+            };
+
+            let mut config = prost_build::Config::new();
+            config.out_dir(&src_dir);
+            toml_config.apply_to_prost(&mut config);
+            prost_reflect_build::Builder::new()
+                .file_descriptor_set_path(&descriptor_path)
+                .configure(&mut config, &proto_paths[..], &self.roots[..])
+                .context(format!(
+                    "generate reflective protobuf ({})",
+                    src_dir.display()
+                ))?;
+            let tonic_builder = toml_config.apply_to_tonic(
+                tonic_build::configure().out_dir(&src_dir), // needed because tonic looks in a different place for the out dir than prost
+            );
+            tonic_builder
+                .compile_with_config(config, &proto_paths[..], &self.roots[..])
+                .context(format!("generate protobuf ({})", src_dir.display()))?;
+        }
+        // Generate a lib.rs file containing all the module definitions and use statements.
+        let lib_rs_path = src_dir.join("lib.rs");
+        {
+            let mut scope = Scope::new();
+            scope.raw("#![allow(clippy::wrong_self_convention)]");
+            scope.raw("#![allow(clippy::large_enum_variant)]");
+            scope.raw("#![allow(clippy::unreadable_literal)]");
+
+            // Adding getter for descriptor pool
+            scope.raw("use prost_reflect::DescriptorPool;");
+            scope.raw("use once_cell::sync::Lazy;");
+            let descriptor_rel_path = strip_prefix(descriptor_path, &self.output_dir);
+            let line = format!(
+                "static DESCRIPTOR_POOL: Lazy<DescriptorPool>
+        = Lazy::new(|| DescriptorPool::decode(include_bytes!(\"..{}\").as_ref()).unwrap());",
+                descriptor_rel_path.display()
+            );
+            scope.raw(line.as_str());
+
+            if self.reflection {
+                scope.raw("#[cfg(feature = \"reflection\")]");
+                scope.raw(&format!(
+                    "pub fn reflection_service(\n) -> tonic_reflection::server::ServerReflectionServer<impl tonic_reflection::server::ServerReflection> {{\n    tonic_reflection::server::Builder::configure()\n        .register_encoded_file_descriptor_set(include_bytes!(\"..{}\"))\n        .build()\n        .unwrap()\n}}",
+                    descriptor_rel_path.display()
+                ));
+            }
+
+            if self.json {
+                // Dynamic (de)serialization between the canonical protobuf JSON mapping and the
+                // generated messages, looking the message type up in DESCRIPTOR_POOL and using
+                // prost_reflect's `serde` support for the proto3 JSON mapping.
+                scope.raw("#[cfg(feature = \"json\")]");
+                scope.raw("use prost::Message;");
+                scope.raw(
+                    "#[cfg(feature = \"json\")]
+pub fn to_json<T: prost_reflect::ReflectMessage>(message: &T) -> serde_json::Value {
+    let full_name = message.descriptor().full_name().to_owned();
+    let descriptor = DESCRIPTOR_POOL
+        .get_message_by_name(&full_name)
+        .unwrap_or_else(|| panic!(\"message `{}` not found in descriptor pool\", full_name));
+    let dynamic =
+        prost_reflect::DynamicMessage::decode(descriptor, message.encode_to_vec().as_slice())
+            .unwrap();
+    serde_json::to_value(&dynamic).unwrap()
+}",
+                );
+                scope.raw(
+                    "#[cfg(feature = \"json\")]
+pub fn from_json<T: prost_reflect::ReflectMessage + Default>(
+    value: &serde_json::Value,
+) -> anyhow::Result<T> {
+    let full_name = T::default().descriptor().full_name().to_owned();
+    let descriptor = DESCRIPTOR_POOL
+        .get_message_by_name(&full_name)
+        .ok_or_else(|| anyhow::anyhow!(\"message `{}` not found in descriptor pool\", full_name))?;
+    let dynamic = prost_reflect::DynamicMessage::deserialize(descriptor, value.clone())?;
+    Ok(T::decode(dynamic.encode_to_vec().as_slice())?)
+}",
+                );
+            }
+
+            Module::build(Path::new(&src_dir), &[&lib_rs_path], &self.external_module)?
+                .codegen(&mut scope);
+            File::create(&lib_rs_path)
+                .context("create lib.rs")?
+                .write_all(scope.to_string().as_bytes())
+                .context("write lib.rs")?;
+        }
+        if self.rustfmt {
+            // Format with rustfmt if it is available otherwise skip it.
+            if let Err(err) = Command::new("rustfmt")
+                .args(&["--edition", "2018", lib_rs_path.to_str().unwrap()])
+                .spawn()
+            {
+                println!("Failed to format lib.rs: {:?}", err);
+            }
+        }
+        // Copy the Cargo template and set version
+        write_cargo_toml(
+            self.cargo_toml_template.clone(),
+            &self.output_dir.join("Cargo.toml"),
+            &self.pkg_name,
+            self.pkg_author.clone(),
+            &self.pkg_version,
+        )
+    }
+}
+
+/// Builds a [`Generator`].
+#[derive(Clone, Debug)]
+pub struct GeneratorBuilder {
+    generator: Generator,
+}
+
+impl GeneratorBuilder {
+    fn new(output_dir: PathBuf, pkg_name: String) -> Self {
+        GeneratorBuilder {
+            generator: Generator {
+                output_dir,
+                roots: Vec::new(),
+                pkg_name,
+                pkg_version: "0.1.0".to_owned(),
+                pkg_author: Vec::new(),
+                cargo_toml_template: None,
+                rustfmt: true,
+                descriptor_set_path: None,
+                entry: Vec::new(),
+                entry_file: Vec::new(),
+                external_module: HashMap::new(),
+                reflection: false,
+                config_path: None,
+                json: false,
+            },
+        }
+    }
+
+    /// Adds a root directory of the protobuf tree to compile (can be called multiple times).
+    pub fn root(mut self, root: impl Into<String>) -> Self {
+        self.generator.roots.push(root.into());
+        self
+    }
+
+    /// Adds several root directories of the protobuf tree to compile.
+    pub fn roots(mut self, roots: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.generator.roots.extend(roots.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets the generated crate's version. Defaults to `0.1.0`.
+    pub fn pkg_version(mut self, pkg_version: impl Into<String>) -> Self {
+        self.generator.pkg_version = pkg_version.into();
+        self
+    }
+
+    /// Adds an author of the generated crate (can be called multiple times).
+    pub fn pkg_author(mut self, pkg_author: impl Into<String>) -> Self {
+        self.generator.pkg_author.push(pkg_author.into());
+        self
+    }
+
+    /// Sets the Cargo.toml template file to use. Defaults to the built-in template.
+    pub fn cargo_toml_template(mut self, cargo_toml_template: impl Into<PathBuf>) -> Self {
+        self.generator.cargo_toml_template = Some(cargo_toml_template.into());
+        self
+    }
+
+    /// Sets whether rustfmt should be run on the generated code. Defaults to `true`.
+    pub fn rustfmt(mut self, rustfmt: bool) -> Self {
+        self.generator.rustfmt = rustfmt;
+        self
+    }
+
+    /// Sets where the file descriptor set is written. Defaults to
+    /// `<output_dir>/resources/file_descriptor_set.bin`.
+    pub fn descriptor_set_path(mut self, descriptor_set_path: impl Into<PathBuf>) -> Self {
+        self.generator.descriptor_set_path = Some(descriptor_set_path.into());
+        self
+    }
+
+    /// Only compiles protos transitively reachable from this package or symbol, e.g.
+    /// `foo.bar.v1` (can be called multiple times).
+    pub fn entry(mut self, entry: impl Into<String>) -> Self {
+        self.generator.entry.push(entry.into());
+        self
+    }
+
+    /// Only compiles protos transitively reachable from this file (can be called multiple
+    /// times).
+    pub fn entry_file(mut self, entry_file: impl Into<PathBuf>) -> Self {
+        self.generator.entry_file.push(entry_file.into());
+        self
+    }
+
+    /// Maps a protobuf package to an external Rust module path, so the generated crate
+    /// re-exports the external crate's types for that package instead of generating its own
+    /// (can be called multiple times).
+    pub fn external_module(
+        mut self,
+        proto_package: impl Into<String>,
+        rust_path: impl Into<String>,
+    ) -> Self {
+        self.generator
+            .external_module
+            .insert(proto_package.into(), rust_path.into());
+        self
+    }
+
+    /// Emits a `reflection_service()` helper (behind the generated crate's `reflection`
+    /// feature) that builds a gRPC server-reflection service from the embedded descriptor
+    /// set. Defaults to `false`.
+    pub fn reflection(mut self, reflection: bool) -> Self {
+        self.generator.reflection = reflection;
+        self
+    }
+
+    /// Sets a TOML file customizing the underlying `prost_build`/`tonic_build` configuration
+    /// (type/field attributes, `bytes`, `btree_map`, `extern_path`, and which of the tonic
+    /// server/client to generate). See [`Config`] for the supported keys.
+    pub fn config(mut self, config_path: impl Into<PathBuf>) -> Self {
+        self.generator.config_path = Some(config_path.into());
+        self
+    }
+
+    /// Emits `to_json`/`from_json` helpers (behind the generated crate's `json` feature) that
+    /// convert messages to and from the canonical protobuf JSON mapping via the embedded
+    /// descriptor pool. Defaults to `false`.
+    pub fn json(mut self, json: bool) -> Self {
+        self.generator.json = json;
+        self
+    }
+
+    /// Builds the [`Generator`].
+    pub fn build(self) -> Generator {
+        self.generator
+    }
+}
+
+fn strip_prefix(path: PathBuf, prefix: &Path) -> PathBuf {
+    if !path.starts_with(prefix) {
+        return path;
+    }
+
+    PathBuf::from(path.to_str().unwrap()[prefix.to_str().unwrap().len()..].to_owned())
+}
+
+fn write_cargo_toml(
+    template_path: Option<PathBuf>,
+    output_path: &Path,
+    pkg_name: &str,
+    pkg_authors: Vec<String>,
+    pkg_version: &str,
+) -> Result<()> {
+    let content = if let Some(template_path) = template_path {
+        // Read template file
+        let mut content = String::new();
+        let mut template_file = File::open(template_path).context("open template")?;
+        template_file
+            .read_to_string(&mut content)
+            .context("read template")?;
+        content
+    } else {
+        // Use default template if no file was provided
+        include_str!("Cargo.toml.tmpl").to_string()
+    };
+    let content = content
+        .replace("_PKG_NAME_", &format!("\"{}\"", pkg_name))
+        .replace(
+            "_PKG_AUTHORS_",
+            &pkg_authors
+                .iter()
+                .map(|v| format!("\"{}\"", v))
+                .collect::<Vec<String>>()
+                .join(","),
+        )
+        .replace("_PKG_VERSION_", &format!("\"{}\"", pkg_version));
+    File::create(output_path)
+        .context("error creating Cargo.toml")?
+        .write_all(content.as_bytes())
+        .context("error writing Cargo.toml")
+}
+
+/// Builds and runs a [`Generator`] writing into `OUT_DIR`, for the common case of calling this
+/// from a `build.rs`:
+///
+/// ```no_run
+/// fn main() {
+///     protocrate::compile_protos!("my_pkg", ["protos/"]).unwrap();
+/// }
+/// ```
+///
+/// The generated module tree still needs to be wired up with `include!` in the crate using it,
+/// e.g. `include!(concat!(env!("OUT_DIR"), "/src/lib.rs"));`.
+#[macro_export]
+macro_rules! compile_protos {
+    ($pkg_name:expr, [$($root:expr),+ $(,)?]) => {
+        $crate::Generator::builder(::std::env::var("OUT_DIR").unwrap(), $pkg_name)
+            $(.root($root))+
+            .build()
+            .generate()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip() {
+        let path = PathBuf::from("path/to/jassob");
+        let prefix = PathBuf::from("path/to/");
+
+        assert_eq!("jassob", strip_prefix(path, &prefix).to_str().unwrap())
+    }
+}
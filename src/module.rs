@@ -11,10 +11,15 @@ pub struct Module {
     child_mod: HashMap<String, Module>,
     priv_mod: Vec<String>,
     use_mod: Vec<String>,
+    external_use: Vec<String>,
 }
 
 impl Module {
-    pub fn build(src_path: &Path, ignore_files: &[&Path]) -> Result<Self> {
+    pub fn build(
+        src_path: &Path,
+        ignore_files: &[&Path],
+        external_modules: &HashMap<String, String>,
+    ) -> Result<Self> {
         let mut root = Module::default();
         let mut file_paths: Vec<PathBuf> = fs::read_dir(src_path)
             .context("read src dir")?
@@ -37,6 +42,21 @@ impl Module {
                 .context("file stem to string")?
                 .replace("r#", "");
             let mod_path: Vec<&str> = file_stem.split('.').collect();
+
+            // Packages mapped (by prefix) to an external crate are re-exported in place rather
+            // than generated: the file produced by prost/tonic for that package is left
+            // untouched (and thus never `mod`-declared, so it's simply not compiled).
+            if let Some((prefix_len, rust_path)) = longest_external_module_prefix(external_modules, &mod_path) {
+                let suffix = &mod_path[prefix_len..];
+                let rust_path = if suffix.is_empty() {
+                    rust_path.to_owned()
+                } else {
+                    format!("{}::{}", rust_path, suffix.join("::"))
+                };
+                root.path_to_external_use(&rust_path, &mod_path);
+                continue;
+            }
+
             let internal_mod_name = file_stem.replace('.', "_") + "_internal";
             {
                 let new_file_name = internal_mod_name.clone() + ".rs";
@@ -69,6 +89,19 @@ impl Module {
                 .push(escape_reserved_keywords(mod_name).trim().to_owned());
         }
     }
+    // Wires a `pub use <rust_path>::*;` re-export at the module node addressed by `path`,
+    // instead of declaring an internal module backed by a generated file.
+    fn path_to_external_use(&mut self, rust_path: &str, path: &[&str]) {
+        if !path.is_empty() {
+            let child = self
+                .child_mod
+                .entry(escape_reserved_keywords(path[0]))
+                .or_insert_with(Module::default);
+            child.path_to_external_use(rust_path, &path[1..]);
+        } else {
+            self.external_use.push(rust_path.to_owned());
+        }
+    }
     fn sorted_children(&self) -> Vec<(&str, &Module)> {
         let mut child_mod: Vec<(&str, &Module)> = self
             .child_mod
@@ -88,6 +121,11 @@ impl Module {
         mods.sort_unstable();
         mods
     }
+    fn sorted_external_use(&self) -> Vec<&str> {
+        let mut paths: Vec<&str> = self.external_use.iter().map(|s| s.as_str()).collect();
+        paths.sort_unstable();
+        paths
+    }
     pub fn codegen(&self, scope: &mut Scope) {
         // Declare internal modules.
         for mod_name in self.sorted_priv_modules() {
@@ -104,9 +142,28 @@ impl Module {
                 .import(&format!("super::{}", mod_name), "*")
                 .vis("pub");
         }
+        // Re-export modules mapped to an external crate.
+        for rust_path in self.sorted_external_use() {
+            scope.import(rust_path, "*").vis("pub");
+        }
     }
 }
 
+// Finds the longest package-path prefix of `mod_path` mapped in `external_modules`, e.g. a file
+// for package `google.protobuf.compiler` matches a mapping for `google.protobuf`. Returns the
+// number of leading segments consumed and the mapped rust path.
+fn longest_external_module_prefix<'a>(
+    external_modules: &'a HashMap<String, String>,
+    mod_path: &[&str],
+) -> Option<(usize, &'a str)> {
+    (1..=mod_path.len()).rev().find_map(|len| {
+        let prefix = mod_path[..len].join(".");
+        external_modules
+            .get(&prefix)
+            .map(|rust_path| (len, rust_path.as_str()))
+    })
+}
+
 // Modules with name matching Rust reserved keywords needs escaping.
 // Most of them can use the raw identifier (r#) to work around the overlap while some will
 // be postfixed with '_'.
@@ -157,7 +214,7 @@ mod tests {
         create_files(&[&root.path().join("foo.rs")]);
 
         // When
-        let module = Module::build(&root.path(), &[]).unwrap();
+        let module = Module::build(&root.path(), &[], &HashMap::new()).unwrap();
 
         // Then
         let mut scope = Scope::new();
@@ -181,7 +238,7 @@ mod tests {
         create_files(&[&root.path().join("foo.v1.rs")]);
 
         // When
-        let module = Module::build(&root.path(), &[]).unwrap();
+        let module = Module::build(&root.path(), &[], &HashMap::new()).unwrap();
 
         // Then
         let mut scope = Scope::new();
@@ -211,7 +268,7 @@ mod tests {
         ]);
 
         // When
-        let module = Module::build(&root.path(), &[]).unwrap();
+        let module = Module::build(&root.path(), &[], &HashMap::new()).unwrap();
 
         // Then
         let mut scope = Scope::new();
@@ -239,4 +296,77 @@ mod tests {
             )
         );
     }
+    #[test]
+    fn external_module_is_reexported_in_place() {
+        // Given
+        let root = TempDir::new("root").unwrap();
+        create_files(&[
+            &root.path().join("google.protobuf.rs"),
+            &root.path().join("foo.v1.rs"),
+        ]);
+        let mut external_modules = HashMap::new();
+        external_modules.insert(
+            "google.protobuf".to_owned(),
+            "other_crate::google::protobuf".to_owned(),
+        );
+
+        // When
+        let module = Module::build(&root.path(), &[], &external_modules).unwrap();
+
+        // Then
+        let mut scope = Scope::new();
+        module.codegen(&mut scope);
+        assert_eq!(
+            strip(&scope.to_string()),
+            strip(
+                r#"
+                pub mod foo {
+                    mod foo_v1_internal;
+                    pub mod v1 {
+                        pub use super::foo_v1_internal::*;
+                    }
+                }
+                pub mod google {
+                    pub mod protobuf {
+                        pub use other_crate::google::protobuf::*;
+                    }
+                }
+                "#
+            )
+        );
+        assert!(root.path().join("google.protobuf.rs").exists());
+    }
+    #[test]
+    fn external_module_mapping_matches_sub_packages_by_prefix() {
+        // Given
+        let root = TempDir::new("root").unwrap();
+        create_files(&[&root.path().join("google.protobuf.compiler.rs")]);
+        let mut external_modules = HashMap::new();
+        external_modules.insert(
+            "google.protobuf".to_owned(),
+            "other_crate::google::protobuf".to_owned(),
+        );
+
+        // When
+        let module = Module::build(&root.path(), &[], &external_modules).unwrap();
+
+        // Then
+        let mut scope = Scope::new();
+        module.codegen(&mut scope);
+        assert_eq!(
+            strip(&scope.to_string()),
+            strip(
+                r#"
+                pub mod google {
+                    pub mod protobuf {
+                        pub mod compiler {
+                            pub use other_crate::google::protobuf::compiler::*;
+                        }
+                    }
+                }
+                "#
+            )
+        );
+        assert!(root.path().join("google.protobuf.compiler.rs").exists());
+    }
 }
@@ -0,0 +1,331 @@
+use crate::error::Error;
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// A seed from which the transitively reachable set of `.proto` files is computed.
+#[derive(Clone, Debug)]
+pub enum EntryPoint {
+    /// A protobuf package or fully-qualified symbol, e.g. `foo.bar.v1`.
+    Package(String),
+    /// A path to a `.proto` file.
+    File(PathBuf),
+}
+
+// An `import` statement parsed out of a `.proto` file.
+struct Import {
+    path: String,
+    // Imports marked `weak` are optional: they are skipped rather than treated as fatal when
+    // they cannot be resolved.
+    weak: bool,
+}
+
+// A file currently being visited, together with the imports left to follow.
+struct Frame {
+    file: PathBuf,
+    imports: std::vec::IntoIter<Import>,
+}
+
+/// Resolves the transitive closure of `.proto` files reachable from `entries` by following
+/// `import` statements, rooted at `roots`. The returned paths are in a valid compilation order,
+/// i.e. a file never appears before any of its (non-weak) imports.
+pub fn resolve(roots: &[String], entries: &[EntryPoint]) -> Result<Vec<String>> {
+    let packages = index_packages(roots)?;
+
+    let mut seeds: Vec<PathBuf> = Vec::new();
+    for entry in entries {
+        match entry {
+            EntryPoint::File(path) => seeds.push(path.clone()),
+            EntryPoint::Package(package_or_symbol) => {
+                seeds.extend(resolve_package_or_symbol(&packages, package_or_symbol)?.iter().cloned());
+            }
+        }
+    }
+
+    let mut in_chain: HashSet<PathBuf> = HashSet::new();
+    let mut done: HashSet<PathBuf> = HashSet::new();
+    let mut ordered: Vec<PathBuf> = Vec::new();
+
+    for seed in seeds {
+        if done.contains(&seed) {
+            continue;
+        }
+        let mut frames: Vec<Frame> = vec![push_frame(&seed)?];
+        in_chain.insert(seed.clone());
+
+        while let Some(next_import) = frames.last_mut().map(|frame| frame.imports.next()) {
+            match next_import {
+                Some(import) => {
+                    let importer = frames.last().unwrap().file.clone();
+                    let resolved = match resolve_import(&import.path, roots) {
+                        Some(resolved) => resolved,
+                        None if import.weak => continue,
+                        None => {
+                            let searched = roots
+                                .iter()
+                                .map(|root| Path::new(root).join(&import.path))
+                                .collect();
+                            return Err(Error::MissingImportFile {
+                                importer: importer.display().to_string(),
+                                import: import.path,
+                                searched,
+                            }
+                            .into())
+                        }
+                    };
+                    if done.contains(&resolved) {
+                        continue;
+                    }
+                    if in_chain.contains(&resolved) {
+                        return Err(Error::CircularImport {
+                            current: importer.display().to_string(),
+                            import: resolved.display().to_string(),
+                        }
+                        .into());
+                    }
+                    in_chain.insert(resolved.clone());
+                    frames.push(push_frame(&resolved)?);
+                }
+                None => {
+                    let frame = frames.pop().unwrap();
+                    in_chain.remove(&frame.file);
+                    done.insert(frame.file.clone());
+                    ordered.push(frame.file);
+                }
+            }
+        }
+    }
+
+    Ok(ordered
+        .iter()
+        .map(|path| path.to_str().unwrap().to_owned())
+        .collect())
+}
+
+fn push_frame(file: &Path) -> Result<Frame> {
+    Ok(Frame {
+        file: file.to_path_buf(),
+        imports: parse_imports(file)?.into_iter(),
+    })
+}
+
+// Parses the `import` statements out of a `.proto` file, in the order they appear.
+fn parse_imports(file: &Path) -> Result<Vec<Import>> {
+    let content =
+        fs::read_to_string(file).with_context(|| format!("read proto file ({})", file.display()))?;
+    let mut imports = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        let rest = match line.strip_prefix("import ") {
+            Some(rest) => rest,
+            None => continue,
+        };
+        let (weak, rest) = match rest.strip_prefix("weak ") {
+            Some(rest) => (true, rest),
+            None => match rest.strip_prefix("public ") {
+                Some(rest) => (false, rest),
+                None => (false, rest),
+            },
+        };
+        let rest = rest.trim().trim_end_matches(';').trim();
+        if let Some(path) = rest.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+            imports.push(Import {
+                path: path.to_owned(),
+                weak,
+            });
+        }
+    }
+    Ok(imports)
+}
+
+// Resolves an imported path relative to each root in turn, returning the first match.
+fn resolve_import(import: &str, roots: &[String]) -> Option<PathBuf> {
+    roots
+        .iter()
+        .map(|root| Path::new(root).join(import))
+        .find(|candidate| candidate.is_file())
+}
+
+// Resolves a package-or-symbol entry point (e.g. `foo.bar.v1` or `foo.bar.v1.MyMessage`) to the
+// file(s) declaring it, by trying the full dotted path as a package name and, failing that,
+// progressively stripping trailing segments (symbol names) until a declaring package is found.
+fn resolve_package_or_symbol<'a>(
+    packages: &'a HashMap<String, Vec<PathBuf>>,
+    package_or_symbol: &str,
+) -> Result<&'a [PathBuf]> {
+    let mut candidate = package_or_symbol;
+    loop {
+        if let Some(files) = packages.get(candidate) {
+            return Ok(files);
+        }
+        candidate = match candidate.rfind('.') {
+            Some(idx) => &candidate[..idx],
+            None => {
+                return Err(anyhow::anyhow!(
+                    "no proto file declares package or symbol `{}`",
+                    package_or_symbol
+                ))
+            }
+        };
+    }
+}
+
+// Maps each protobuf package declared under `roots` to the file(s) that declare it, so
+// package-based entry points (e.g. `foo.bar.v1`) can be turned into seed files.
+fn index_packages(roots: &[String]) -> Result<HashMap<String, Vec<PathBuf>>> {
+    let mut packages: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for root in roots {
+        for entry in WalkDir::new(root)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension() == Some(OsStr::new("proto")))
+        {
+            let path = entry.path().to_path_buf();
+            if let Some(package) = parse_package(&path)? {
+                packages.entry(package).or_insert_with(Vec::new).push(path);
+            }
+        }
+    }
+    Ok(packages)
+}
+
+// Parses the `package` statement out of a `.proto` file, if any.
+fn parse_package(file: &Path) -> Result<Option<String>> {
+    let content =
+        fs::read_to_string(file).with_context(|| format!("read proto file ({})", file.display()))?;
+    for line in content.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("package ") {
+            let package = rest.trim().trim_end_matches(';').trim();
+            return Ok(Some(package.to_owned()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    fn write_proto(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(path).unwrap().write_all(content.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn resolves_symbol_entry_point_via_owning_package() {
+        // Given
+        let root = TempDir::new("root").unwrap();
+        write_proto(
+            &root.path().join("a.proto"),
+            "syntax = \"proto3\";\npackage foo.bar.v1;\n",
+        );
+
+        // When
+        let roots = vec![root.path().to_str().unwrap().to_owned()];
+        let entries = vec![EntryPoint::Package("foo.bar.v1.MyMessage".to_owned())];
+        let result = resolve(&roots, &entries).unwrap();
+
+        // Then
+        assert_eq!(result, vec![root.path().join("a.proto").to_str().unwrap().to_owned()]);
+    }
+
+    #[test]
+    fn resolves_transitive_imports() {
+        // Given
+        let root = TempDir::new("root").unwrap();
+        write_proto(
+            &root.path().join("a.proto"),
+            "syntax = \"proto3\";\npackage a;\nimport \"b.proto\";\n",
+        );
+        write_proto(
+            &root.path().join("b.proto"),
+            "syntax = \"proto3\";\npackage b;\n",
+        );
+        write_proto(
+            &root.path().join("unused.proto"),
+            "syntax = \"proto3\";\npackage unused;\n",
+        );
+
+        // When
+        let roots = vec![root.path().to_str().unwrap().to_owned()];
+        let entries = vec![EntryPoint::File(root.path().join("a.proto"))];
+        let mut result = resolve(&roots, &entries).unwrap();
+        result.sort();
+
+        // Then
+        let mut expected = vec![
+            root.path().join("a.proto").to_str().unwrap().to_owned(),
+            root.path().join("b.proto").to_str().unwrap().to_owned(),
+        ];
+        expected.sort();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn skips_missing_weak_import() {
+        // Given
+        let root = TempDir::new("root").unwrap();
+        write_proto(
+            &root.path().join("a.proto"),
+            "syntax = \"proto3\";\npackage a;\nimport weak \"missing.proto\";\n",
+        );
+
+        // When
+        let roots = vec![root.path().to_str().unwrap().to_owned()];
+        let entries = vec![EntryPoint::File(root.path().join("a.proto"))];
+        let result = resolve(&roots, &entries).unwrap();
+
+        // Then
+        assert_eq!(result, vec![root.path().join("a.proto").to_str().unwrap().to_owned()]);
+    }
+
+    #[test]
+    fn fails_on_missing_required_import() {
+        // Given
+        let root = TempDir::new("root").unwrap();
+        write_proto(
+            &root.path().join("a.proto"),
+            "syntax = \"proto3\";\npackage a;\nimport \"missing.proto\";\n",
+        );
+
+        // When
+        let roots = vec![root.path().to_str().unwrap().to_owned()];
+        let entries = vec![EntryPoint::File(root.path().join("a.proto"))];
+        let err = resolve(&roots, &entries).unwrap_err();
+
+        // Then
+        assert!(err.downcast_ref::<Error>().is_some());
+    }
+
+    #[test]
+    fn fails_on_circular_import() {
+        // Given
+        let root = TempDir::new("root").unwrap();
+        write_proto(
+            &root.path().join("a.proto"),
+            "syntax = \"proto3\";\npackage a;\nimport \"b.proto\";\n",
+        );
+        write_proto(
+            &root.path().join("b.proto"),
+            "syntax = \"proto3\";\npackage b;\nimport \"a.proto\";\n",
+        );
+
+        // When
+        let roots = vec![root.path().to_str().unwrap().to_owned()];
+        let entries = vec![EntryPoint::File(root.path().join("a.proto"))];
+        let err = resolve(&roots, &entries).unwrap_err();
+
+        // Then
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::CircularImport { .. })
+        ));
+    }
+}